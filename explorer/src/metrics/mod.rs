@@ -0,0 +1,129 @@
+use once_cell::sync::Lazy;
+use poem::{handler, IntoResponse, Response};
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use std::time::Instant;
+
+pub static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+pub static HTTP_REQUESTS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    let counter = IntCounterVec::new(
+        Opts::new("scanner_http_requests_total", "Total requests received, by endpoint"),
+        &["endpoint"],
+    )
+    .unwrap();
+    REGISTRY.register(Box::new(counter.clone())).unwrap();
+    counter
+});
+
+pub static HTTP_REQUEST_DURATION_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    let histogram = HistogramVec::new(
+        HistogramOpts::new("scanner_http_request_duration_seconds", "Request latency, by endpoint"),
+        &["endpoint"],
+    )
+    .unwrap();
+    REGISTRY.register(Box::new(histogram.clone())).unwrap();
+    histogram
+});
+
+pub static HTTP_ERRORS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    let counter = IntCounterVec::new(
+        Opts::new("scanner_http_errors_total", "Internal errors returned, by endpoint and code"),
+        &["endpoint", "code"],
+    )
+    .unwrap();
+    REGISTRY.register(Box::new(counter.clone())).unwrap();
+    counter
+});
+
+pub static DB_QUERY_DURATION_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    let histogram = HistogramVec::new(
+        HistogramOpts::new("scanner_db_query_duration_seconds", "Duration of individual named queries"),
+        &["query"],
+    )
+    .unwrap();
+    REGISTRY.register(Box::new(histogram.clone())).unwrap();
+    histogram
+});
+
+pub static INDEXED_HEIGHT: Lazy<IntGauge> = Lazy::new(|| {
+    let gauge = IntGauge::new("scanner_indexed_height", "Height the statistics job has folded up to").unwrap();
+    REGISTRY.register(Box::new(gauge.clone())).unwrap();
+    gauge
+});
+
+pub static CHAIN_TIP_HEIGHT: Lazy<IntGauge> = Lazy::new(|| {
+    let gauge = IntGauge::new("scanner_chain_tip_height", "Highest height in the indexed transaction table; the live chain tip is not read").unwrap();
+    REGISTRY.register(Box::new(gauge.clone())).unwrap();
+    gauge
+});
+
+/// Tracks one request against `endpoint`: increments its counter on creation
+/// and observes the elapsed time into the latency histogram on drop, so a
+/// handler only needs `let _timer = metrics::RequestTimer::start("...");` at
+/// the top regardless of which branch it returns through.
+pub struct RequestTimer {
+    endpoint: &'static str,
+    started: Instant,
+}
+
+impl RequestTimer {
+    pub fn start(endpoint: &'static str) -> Self {
+        HTTP_REQUESTS_TOTAL.with_label_values(&[endpoint]).inc();
+        Self {
+            endpoint,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn record_error(&self, code: i32) {
+        HTTP_ERRORS_TOTAL
+            .with_label_values(&[self.endpoint, &code.to_string()])
+            .inc();
+    }
+}
+
+impl Drop for RequestTimer {
+    fn drop(&mut self) {
+        HTTP_REQUEST_DURATION_SECONDS
+            .with_label_values(&[self.endpoint])
+            .observe(self.started.elapsed().as_secs_f64());
+    }
+}
+
+/// Times a single named DB query into `scanner_db_query_duration_seconds`.
+pub struct QueryTimer {
+    query: &'static str,
+    started: Instant,
+}
+
+impl QueryTimer {
+    pub fn start(query: &'static str) -> Self {
+        Self {
+            query,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for QueryTimer {
+    fn drop(&mut self) {
+        DB_QUERY_DURATION_SECONDS
+            .with_label_values(&[self.query])
+            .observe(self.started.elapsed().as_secs_f64());
+    }
+}
+
+pub fn set_indexer_lag(indexed_height: i64, chain_tip_height: i64) {
+    INDEXED_HEIGHT.set(indexed_height);
+    CHAIN_TIP_HEIGHT.set(chain_tip_height);
+}
+
+#[handler]
+pub fn metrics() -> impl IntoResponse {
+    let metric_families = REGISTRY.gather();
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&metric_families, &mut buffer).unwrap();
+    Response::builder()
+        .content_type("text/plain; version=0.0.4")
+        .body(buffer)
+}
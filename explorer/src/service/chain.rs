@@ -1,3 +1,5 @@
+use crate::metrics;
+use crate::supply::supply;
 use crate::Api;
 use anyhow::Result;
 use module::schema::DelegationInfo;
@@ -5,10 +7,8 @@ use poem_openapi::param::Query;
 use poem_openapi::{payload::Json, ApiResponse, Object};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use sqlx::types::chrono::Local;
 use sqlx::Error::RowNotFound;
 use sqlx::Row;
-use std::collections::HashSet;
 
 #[derive(ApiResponse)]
 pub enum ChainStatisticsResponse {
@@ -30,6 +30,33 @@ pub struct StatisticsData {
     pub daily_txs: i64,
 }
 
+#[derive(ApiResponse)]
+pub enum LargestAddressesResponse {
+    #[oai(status = 200)]
+    Ok(Json<LargestAddressesRes>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Object)]
+pub struct LargestAddressesRes {
+    pub code: i32,
+    pub message: String,
+    pub data: Option<LargestAddressesData>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Object)]
+pub struct LargestAddressesData {
+    pub total: i64,
+    pub circulating: i64,
+    pub accounts: Vec<AddressBalance>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Object)]
+pub struct AddressBalance {
+    pub address: String,
+    pub balance: i64,
+    pub rank: i64,
+}
+
 #[derive(ApiResponse)]
 pub enum StakingResponse {
     #[oai(status = 200)]
@@ -49,90 +76,221 @@ pub struct StakingData {
     pub stake_ratio: f64,
     pub apy: f64,
     pub active_validators: Vec<String>,
+    pub total_supply: i64,
+    pub circulating_supply: i64,
+    pub non_circulating_supply: i64,
+}
+
+#[derive(ApiResponse)]
+pub enum SupplyResponse {
+    #[oai(status = 200)]
+    Ok(Json<SupplyRes>),
 }
 
+#[derive(Serialize, Deserialize, Debug, Default, Object)]
+pub struct SupplyRes {
+    pub code: i32,
+    pub message: String,
+    pub data: Option<SupplyData>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Object)]
+pub struct SupplyData {
+    pub total: i64,
+    pub circulating: i64,
+    pub non_circulating: i64,
+}
+
+/// Total/circulating/non-circulating supply, maintained by the
+/// `recompute_supply` background job (see the `supply` module) from observed
+/// issuance rather than a hardcoded constant.
+pub async fn supply_info(api: &Api) -> Result<SupplyResponse> {
+    let _timer = metrics::RequestTimer::start("supply_info");
+
+    let supply_res = {
+        let _query_timer = metrics::QueryTimer::start("supply_cache");
+        supply(api).await
+    };
+    match supply_res {
+        Ok(Some(s)) => Ok(SupplyResponse::Ok(Json(SupplyRes {
+            code: 200,
+            message: "".to_string(),
+            data: Some(SupplyData {
+                total: s.total,
+                circulating: s.circulating,
+                non_circulating: s.non_circulating,
+            }),
+        }))),
+        Ok(None) => Ok(SupplyResponse::Ok(Json(SupplyRes {
+            code: 200,
+            message: "".to_string(),
+            data: Some(SupplyData::default()),
+        }))),
+        Err(_) => {
+            _timer.record_error(50001);
+            Ok(SupplyResponse::Ok(Json(SupplyRes {
+                code: 50001,
+                message: "internal error, supply cache.".to_string(),
+                data: None,
+            })))
+        }
+    }
+}
+
+/// Statistics are served from `statistics_cache`, a single row kept current
+/// by the `recompute_statistics` background job (see the `worker` module),
+/// rather than scanning the `transaction` table on every request.
 pub async fn statistics(api: &Api) -> Result<ChainStatisticsResponse> {
+    let _timer = metrics::RequestTimer::start("statistics");
     let mut conn = api.storage.lock().await.acquire().await?;
 
-    let mut res_data = StatisticsData {
-        active_addresses: 0,
-        total_txs: 0,
-        daily_txs: 0,
+    let sql_str =
+        String::from("SELECT total_txs, daily_txs, active_addresses FROM statistics_cache WHERE id=1");
+    let cache_res = {
+        let _query_timer = metrics::QueryTimer::start("statistics_cache");
+        sqlx::query(sql_str.as_str()).fetch_one(&mut conn).await
     };
-
-    // total txs
-    let sql_str = String::from("SELECT COUNT(*) as cnt FROM transaction");
-    let total_txs_res = sqlx::query(sql_str.as_str()).fetch_one(&mut conn).await;
-    if let Err(ref err) = total_txs_res {
-        match err {
-            RowNotFound => {}
-            _ => {
-                return Ok(ChainStatisticsResponse::Ok(Json(ChainStatisticsRes {
-                    code: 50001,
-                    message: "internal error, total txs.".to_string(),
-                    data: Some(res_data),
-                })));
-            }
+    let row = match cache_res {
+        Ok(row) => row,
+        Err(RowNotFound) => {
+            return Ok(ChainStatisticsResponse::Ok(Json(ChainStatisticsRes {
+                code: 200,
+                message: "".to_string(),
+                data: Some(StatisticsData::default()),
+            })));
         }
-    }
-    let total_txs = total_txs_res.unwrap().try_get("cnt")?;
-
-    // total address
-    let sql_str = String::from("SELECT jsonb_path_query(value,'$.body.operations[*].TransferAsset.body.transfer.outputs[*].public_key') as addr FROM transaction");
-    let active_addresses_res = sqlx::query(sql_str.as_str()).fetch_all(&mut conn).await;
-    if let Err(ref err) = active_addresses_res {
-        match err {
-            RowNotFound => {}
-            _ => {
-                return Ok(ChainStatisticsResponse::Ok(Json(ChainStatisticsRes {
-                    code: 50001,
-                    message: "internal error, total addresses.".to_string(),
-                    data: Some(res_data),
-                })));
-            }
+        Err(_) => {
+            _timer.record_error(50001);
+            return Ok(ChainStatisticsResponse::Ok(Json(ChainStatisticsRes {
+                code: 50001,
+                message: "internal error, statistics cache.".to_string(),
+                data: None,
+            })));
         }
-    }
-    let vec = active_addresses_res.unwrap();
-    let mut hs: HashSet<String> = HashSet::new();
-    for row in vec {
-        let value: Value = row.try_get("addr")?;
-        let addr: String = serde_json::from_value(value).unwrap();
-        hs.insert(addr);
-    }
-    let active_addresses = hs.len() as i64;
+    };
 
-    // daily txs
-    let t = Local::now().timestamp() - 3600 * 24;
-    let daily_txs_res = sqlx::query("SELECT COUNT(*) as cnt FROM transaction where timestamp>=$1")
-        .bind(t)
-        .fetch_one(&mut conn)
-        .await;
-    if let Err(ref err) = daily_txs_res {
-        match err {
-            RowNotFound => {}
-            _ => {
-                return Ok(ChainStatisticsResponse::Ok(Json(ChainStatisticsRes {
-                    code: 50001,
-                    message: "internal error, daily txs.".to_string(),
-                    data: Some(res_data),
-                })));
-            }
+    let res_data = StatisticsData {
+        total_txs: row.try_get("total_txs")?,
+        daily_txs: row.try_get("daily_txs")?,
+        active_addresses: row.try_get("active_addresses")?,
+    };
+
+    Ok(ChainStatisticsResponse::Ok(Json(ChainStatisticsRes {
+        code: 200,
+        message: "".to_string(),
+        data: Some(res_data),
+    })))
+}
+
+/// Top-N addresses by current balance, maintained by the `fold_balances`
+/// background job (see the `worker` module) rather than decoded per request.
+/// `circulating` excludes addresses listed in `non_circulating_address`,
+/// which `supply::sync_non_circulating_accounts` populates from config.
+pub async fn largest_addresses(
+    api: &Api,
+    limit: Query<Option<i64>>,
+    circulating: Query<Option<bool>>,
+) -> Result<LargestAddressesResponse> {
+    let _timer = metrics::RequestTimer::start("largest_addresses");
+    let mut conn = api.storage.lock().await.acquire().await?;
+    let limit = limit.0.unwrap_or(100);
+    let circulating_only = circulating.0.unwrap_or(false);
+
+    let sql_str = if circulating_only {
+        String::from(
+            "SELECT address, balance, RANK() OVER (ORDER BY balance DESC) as rank \
+             FROM address_balance \
+             WHERE address NOT IN (SELECT address FROM non_circulating_address) \
+             ORDER BY balance DESC LIMIT $1",
+        )
+    } else {
+        String::from(
+            "SELECT address, balance, RANK() OVER (ORDER BY balance DESC) as rank \
+             FROM address_balance ORDER BY balance DESC LIMIT $1",
+        )
+    };
+    let rows_res = {
+        let _query_timer = metrics::QueryTimer::start("address_balance");
+        sqlx::query(sql_str.as_str()).bind(limit).fetch_all(&mut conn).await
+    };
+    let rows = match rows_res {
+        Ok(rows) => rows,
+        Err(RowNotFound) => vec![],
+        Err(_) => {
+            _timer.record_error(50001);
+            return Ok(LargestAddressesResponse::Ok(Json(LargestAddressesRes {
+                code: 50001,
+                message: "internal error, largest addresses.".to_string(),
+                data: None,
+            })));
         }
+    };
+    let mut accounts = vec![];
+    for row in rows {
+        accounts.push(AddressBalance {
+            address: row.try_get("address")?,
+            balance: row.try_get("balance")?,
+            rank: row.try_get("rank")?,
+        });
     }
-    let daily_txs = daily_txs_res.unwrap().try_get("cnt")?;
 
-    res_data.daily_txs = daily_txs;
-    res_data.total_txs = total_txs;
-    res_data.active_addresses = active_addresses;
+    let total: i64 = sqlx::query("SELECT COUNT(*) as cnt FROM address_balance")
+        .fetch_one(&mut conn)
+        .await?
+        .try_get("cnt")?;
+    let circulating: i64 = sqlx::query(
+        "SELECT COUNT(*) as cnt FROM address_balance \
+         WHERE address NOT IN (SELECT address FROM non_circulating_address)",
+    )
+    .fetch_one(&mut conn)
+    .await?
+    .try_get("cnt")?;
 
-    Ok(ChainStatisticsResponse::Ok(Json(ChainStatisticsRes {
+    Ok(LargestAddressesResponse::Ok(Json(LargestAddressesRes {
         code: 200,
         message: "".to_string(),
-        data: Some(res_data),
+        data: Some(LargestAddressesData {
+            total,
+            circulating,
+            accounts,
+        }),
     })))
 }
 
+/// Scalar fields pulled out of a decoded `DelegationInfo`, shared between the
+/// live `staking_info` handler and the `recompute_staking_history` background
+/// job so the decode logic only lives in one place.
+pub(crate) struct DecodedDelegation {
+    pub apy: f64,
+    pub block_reward: u64,
+    pub total_stake: u64,
+    pub active_validators: Vec<String>,
+}
+
+pub(crate) fn decode_delegation(delegation_info: &DelegationInfo) -> DecodedDelegation {
+    let mut active_validators: Vec<String> = vec![];
+    for (id, _) in &delegation_info.validator_addr_map {
+        active_validators.push(id.clone());
+    }
+    let mut reward: u64 = 0;
+    let mut total_stake: u64 = 0;
+    for (_, dl) in &delegation_info.global_delegation_records_map {
+        reward += dl.rwd_amount;
+        for (_, amount) in &dl.delegations {
+            total_stake += amount;
+        }
+    }
+
+    DecodedDelegation {
+        apy: delegation_info.return_rate.value,
+        block_reward: reward,
+        total_stake,
+        active_validators,
+    }
+}
+
 pub async fn staking_info(api: &Api, height: Query<Option<i64>>) -> Result<StakingResponse> {
+    let _timer = metrics::RequestTimer::start("staking_info");
     let mut conn = api.storage.lock().await.acquire().await?;
 
     let sql_str = if let Some(height) = height.0 {
@@ -140,7 +298,10 @@ pub async fn staking_info(api: &Api, height: Query<Option<i64>>) -> Result<Staki
     } else {
         "SELECT info FROM delegations ORDER BY height DESC LIMIT 1".to_string()
     };
-    let delegation_res = sqlx::query(sql_str.as_str()).fetch_one(&mut conn).await;
+    let delegation_res = {
+        let _query_timer = metrics::QueryTimer::start("delegations");
+        sqlx::query(sql_str.as_str()).fetch_one(&mut conn).await
+    };
 
     if let Err(ref err) = delegation_res {
         return match err {
@@ -149,34 +310,48 @@ pub async fn staking_info(api: &Api, height: Query<Option<i64>>) -> Result<Staki
                 message: "".to_string(),
                 data: Some(StakingData::default()),
             }))),
-            _ => Ok(StakingResponse::Ok(Json(StakingRes {
-                code: 50001,
-                message: "internal error.".to_string(),
-                data: None,
-            }))),
+            _ => {
+                _timer.record_error(50001);
+                Ok(StakingResponse::Ok(Json(StakingRes {
+                    code: 50001,
+                    message: "internal error.".to_string(),
+                    data: None,
+                })))
+            }
         };
     }
     let info_value: Value = delegation_res.unwrap().try_get("info")?;
     let delegation_info: DelegationInfo = serde_json::from_value(info_value).unwrap();
+    let decoded = decode_delegation(&delegation_info);
 
-    let mut active_validators: Vec<String> = vec![];
-    for (id, _) in delegation_info.validator_addr_map {
-        active_validators.push(id);
-    }
-    let mut reward: u64 = 0;
-    let mut total_stake: u64 = 0;
-    for (_, dl) in delegation_info.global_delegation_records_map {
-        reward += dl.rwd_amount;
-        for (_, amount) in dl.delegations {
-            total_stake += amount
+    let current_supply = {
+        let _query_timer = metrics::QueryTimer::start("supply_cache");
+        match supply(api).await {
+            Ok(supply) => supply.unwrap_or_default(),
+            Err(_) => {
+                _timer.record_error(50001);
+                return Ok(StakingResponse::Ok(Json(StakingRes {
+                    code: 50001,
+                    message: "internal error, supply cache.".to_string(),
+                    data: None,
+                })));
+            }
         }
-    }
+    };
+    let stake_ratio = if current_supply.circulating > 0 {
+        decoded.total_stake as f64 / current_supply.circulating as f64
+    } else {
+        0.0
+    };
 
     let data = StakingData {
-        block_reward: reward,
-        apy: delegation_info.return_rate.value,
-        stake_ratio: total_stake as f64 / 21_420_000_000_000_000.0,
-        active_validators,
+        block_reward: decoded.block_reward,
+        apy: decoded.apy,
+        stake_ratio,
+        active_validators: decoded.active_validators,
+        total_supply: current_supply.total,
+        circulating_supply: current_supply.circulating,
+        non_circulating_supply: current_supply.non_circulating,
     };
 
     Ok(StakingResponse::Ok(Json(StakingRes {
@@ -185,3 +360,94 @@ pub async fn staking_info(api: &Api, height: Query<Option<i64>>) -> Result<Staki
         data: Some(data),
     })))
 }
+
+#[derive(ApiResponse)]
+pub enum StakingHistoryResponse {
+    #[oai(status = 200)]
+    Ok(Json<StakingHistoryRes>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Object)]
+pub struct StakingHistoryRes {
+    pub code: i32,
+    pub message: String,
+    pub data: Option<Vec<StakingHistoryPoint>>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Object)]
+pub struct StakingHistoryPoint {
+    pub height: i64,
+    pub timestamp: i64,
+    pub apy: f64,
+    pub block_reward: i64,
+    pub stake_ratio: f64,
+    pub active_validator_count: i64,
+}
+
+/// Downsampled staking/APY time series read from `staking_history`, which the
+/// `recompute_staking_history` background job populates at index time so a
+/// range query never has to decode `delegations` rows on the fly.
+pub async fn staking_history(
+    api: &Api,
+    from_height: Query<Option<i64>>,
+    to_height: Query<Option<i64>>,
+    step: Query<Option<i64>>,
+) -> Result<StakingHistoryResponse> {
+    let _timer = metrics::RequestTimer::start("staking_history");
+    let mut conn = api.storage.lock().await.acquire().await?;
+    let step = step.0.unwrap_or(1).max(1);
+
+    // `staking_history` rows only exist at heights where a delegation
+    // snapshot actually changed, so an exact `height % step = 0` filter would
+    // match few or none of them. Bucket by `height / step` instead and take
+    // one (the earliest) row per bucket, so "one sample per `step` blocks"
+    // degrades gracefully to whatever heights were actually indexed.
+    let mut sql_str = format!(
+        "SELECT DISTINCT ON (height / {}) \
+         height, timestamp, apy, block_reward, stake_ratio, active_validator_count \
+         FROM staking_history WHERE 1=1",
+        step
+    );
+    if let Some(from) = from_height.0 {
+        sql_str.push_str(&format!(" AND height>={}", from));
+    }
+    if let Some(to) = to_height.0 {
+        sql_str.push_str(&format!(" AND height<={}", to));
+    }
+    sql_str.push_str(&format!(" ORDER BY height / {}, height ASC", step));
+
+    let rows_res = {
+        let _query_timer = metrics::QueryTimer::start("staking_history");
+        sqlx::query(sql_str.as_str()).fetch_all(&mut conn).await
+    };
+    let rows = match rows_res {
+        Ok(rows) => rows,
+        Err(RowNotFound) => vec![],
+        Err(_) => {
+            _timer.record_error(50001);
+            return Ok(StakingHistoryResponse::Ok(Json(StakingHistoryRes {
+                code: 50001,
+                message: "internal error, staking history.".to_string(),
+                data: None,
+            })));
+        }
+    };
+
+    let mut data = vec![];
+    for row in rows {
+        data.push(StakingHistoryPoint {
+            height: row.try_get("height")?,
+            timestamp: row.try_get("timestamp")?,
+            apy: row.try_get("apy")?,
+            block_reward: row.try_get("block_reward")?,
+            stake_ratio: row.try_get("stake_ratio")?,
+            active_validator_count: row.try_get("active_validator_count")?,
+        });
+    }
+
+    Ok(StakingHistoryResponse::Ok(Json(StakingHistoryRes {
+        code: 200,
+        message: "".to_string(),
+        data: Some(data),
+    })))
+}
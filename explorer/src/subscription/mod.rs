@@ -0,0 +1,197 @@
+use futures::{SinkExt, StreamExt};
+use poem::web::websocket::{Message, WebSocket};
+use poem::web::Data;
+use poem::{handler, IntoResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, Mutex};
+
+/// Topics a client can subscribe to. Notification payloads reuse the same
+/// `ChainStatisticsRes`/`StakingRes` shapes the poll-based endpoints return.
+///
+/// `block.new` isn't offered here: there's no block-ingestion code in this
+/// service to push from yet, and a topic nothing ever notifies on is worse
+/// than not advertising it — add it back once indexing wires in a notifier.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    ChainStatistics,
+    StakingInfo,
+}
+
+impl Topic {
+    fn name(&self) -> &'static str {
+        match self {
+            Topic::ChainStatistics => "chain.statistics",
+            Topic::StakingInfo => "staking.info",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Topic> {
+        match name {
+            "chain.statistics" => Some(Topic::ChainStatistics),
+            "staking.info" => Some(Topic::StakingInfo),
+            _ => None,
+        }
+    }
+
+    fn all() -> [Topic; 2] {
+        [Topic::ChainStatistics, Topic::StakingInfo]
+    }
+}
+
+/// Rapid block ingestion collapses into at most one push per topic per this
+/// interval.
+const DEBOUNCE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe { topic: String },
+    Unsubscribe { topic: String },
+}
+
+#[derive(Debug, Serialize)]
+struct Notification {
+    topic: String,
+    data: serde_json::Value,
+}
+
+/// Fan-out hub: one broadcast channel per topic plus the last time each topic
+/// was pushed, shared across all WebSocket connections via `Api`.
+pub struct SubscriptionHub {
+    channels: HashMap<Topic, broadcast::Sender<String>>,
+    last_pushed: Mutex<HashMap<Topic, Instant>>,
+}
+
+impl Default for SubscriptionHub {
+    fn default() -> Self {
+        let mut channels = HashMap::new();
+        for topic in Topic::all() {
+            let (tx, _rx) = broadcast::channel(16);
+            channels.insert(topic, tx);
+        }
+        Self {
+            channels,
+            last_pushed: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl SubscriptionHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn subscribe(&self, topic: Topic) -> broadcast::Receiver<String> {
+        self.channels[&topic].subscribe()
+    }
+
+    /// Pushes `data` to every subscriber of `topic`, unless the topic was
+    /// already pushed within [`DEBOUNCE`].
+    pub async fn notify(&self, topic: Topic, data: serde_json::Value) {
+        let mut last_pushed = self.last_pushed.lock().await;
+        if let Some(at) = last_pushed.get(&topic) {
+            if at.elapsed() < DEBOUNCE {
+                return;
+            }
+        }
+        last_pushed.insert(topic, Instant::now());
+        drop(last_pushed);
+
+        let payload = serde_json::to_string(&Notification {
+            topic: topic.name().to_string(),
+            data,
+        })
+        .unwrap_or_default();
+        let _ = self.channels[&topic].send(payload);
+    }
+}
+
+/// Electrum-style subscription endpoint: clients send `{"method":
+/// "subscribe", "topic": "chain.statistics"}` and receive a notification
+/// frame each time a background job refreshes that topic, whether or not the
+/// value changed; refreshes within [`DEBOUNCE`] of the last push are dropped.
+#[handler]
+pub fn ws(ws: WebSocket, hub: Data<&Arc<SubscriptionHub>>) -> impl IntoResponse {
+    let hub = hub.0.clone();
+    ws.on_upgrade(move |socket| async move {
+        let (sink, mut stream) = socket.split();
+        let sink = Arc::new(Mutex::new(sink));
+        let mut tasks: HashMap<Topic, tokio::task::JoinHandle<()>> = HashMap::new();
+
+        while let Some(Ok(message)) = stream.next().await {
+            let text = match message {
+                Message::Text(text) => text,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            let msg = match serde_json::from_str::<ClientMessage>(&text) {
+                Ok(msg) => msg,
+                Err(_) => continue,
+            };
+            match msg {
+                ClientMessage::Subscribe { topic } => {
+                    let topic = match Topic::parse(&topic) {
+                        Some(topic) => topic,
+                        None => continue,
+                    };
+                    if tasks.contains_key(&topic) {
+                        continue;
+                    }
+                    let mut rx = hub.subscribe(topic);
+                    let sink = sink.clone();
+                    let handle = tokio::spawn(async move {
+                        loop {
+                            let payload = match rx.recv().await {
+                                Ok(payload) => payload,
+                                // A slow client fell behind the broadcast
+                                // channel's buffer; skip the missed
+                                // notifications rather than dropping the
+                                // subscription entirely.
+                                Err(RecvError::Lagged(_)) => continue,
+                                Err(RecvError::Closed) => break,
+                            };
+                            if sink.lock().await.send(Message::Text(payload)).await.is_err() {
+                                break;
+                            }
+                        }
+                    });
+                    tasks.insert(topic, handle);
+                }
+                ClientMessage::Unsubscribe { topic } => {
+                    if let Some(topic) = Topic::parse(&topic) {
+                        if let Some(handle) = tasks.remove(&topic) {
+                            handle.abort();
+                        }
+                    }
+                }
+            }
+        }
+
+        for (_, handle) in tasks {
+            handle.abort();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_topic_round_trips_through_its_wire_name() {
+        for topic in Topic::all() {
+            assert_eq!(Topic::parse(topic.name()), Some(topic));
+        }
+    }
+
+    #[test]
+    fn parse_rejects_unknown_topics() {
+        assert_eq!(Topic::parse("block.new"), None);
+        assert_eq!(Topic::parse(""), None);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::Api;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::Error::RowNotFound;
+use sqlx::Row;
+
+/// Known foundation/locked/unvested addresses that should be excluded from
+/// circulating supply. Loaded from the scanner config rather than hardcoded,
+/// so the non-circulating set can change without a code deploy.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct SupplyConfig {
+    pub non_circulating_addresses: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Supply {
+    pub total: i64,
+    pub circulating: i64,
+    pub non_circulating: i64,
+}
+
+/// Findora's native FRA token is identified on-chain by an all-zero asset
+/// type code; every other code is a custom token. Folding a custom token's
+/// transfers/issuances into native-token balances or supply would silently
+/// misstate both, so any amount pulled out of a transaction must first be
+/// checked against this.
+pub(crate) const FRA_ASSET_TYPE_CODE: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+
+/// Whether `record` (a transfer input/output or issuance record) moves the
+/// native FRA asset rather than some other asset type.
+pub(crate) fn is_native_asset(record: &Value) -> bool {
+    record
+        .get("asset_type")
+        .and_then(|a| a.get("NonConfidential"))
+        .and_then(|v| v.as_str())
+        == Some(FRA_ASSET_TYPE_CODE)
+}
+
+/// Mirrors `config.non_circulating_addresses` into the `non_circulating_address`
+/// table so both the supply cache and `GET /addresses/largest?circulating=`
+/// agree on the same set.
+pub async fn sync_non_circulating_accounts(api: &Api, config: &SupplyConfig) -> Result<()> {
+    let mut conn = api.storage.lock().await.acquire().await?;
+    for address in &config.non_circulating_addresses {
+        sqlx::query("INSERT INTO non_circulating_address (address) VALUES ($1) ON CONFLICT DO NOTHING")
+            .bind(address)
+            .execute(&mut conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Folds `IssueAsset` mint amounts indexed since `supply_cache.last_height`
+/// into a running total supply, then derives circulating supply by
+/// subtracting the balances held by `non_circulating_address`.
+pub async fn recompute_supply(api: &Api) -> Result<()> {
+    let mut conn = api.storage.lock().await.acquire().await?;
+
+    let last_height: i64 = match sqlx::query("SELECT last_height FROM supply_cache WHERE id=1")
+        .fetch_one(&mut conn)
+        .await
+    {
+        Ok(row) => row.try_get("last_height")?,
+        Err(RowNotFound) => 0,
+        Err(err) => return Err(err.into()),
+    };
+
+    let sql_str =
+        String::from("SELECT height, value FROM transaction WHERE height>$1 ORDER BY height ASC");
+    let rows = sqlx::query(sql_str.as_str())
+        .bind(last_height)
+        .fetch_all(&mut conn)
+        .await?;
+
+    let mut minted: i64 = 0;
+    let mut max_height = last_height;
+    for row in &rows {
+        let height: i64 = row.try_get("height")?;
+        if height > max_height {
+            max_height = height;
+        }
+        let value: Value = row.try_get("value")?;
+        minted += extract_issued_amount(&value);
+    }
+
+    let non_circulating: i64 = sqlx::query(
+        "SELECT COALESCE(SUM(balance), 0) as sum FROM address_balance \
+         WHERE address IN (SELECT address FROM non_circulating_address)",
+    )
+    .fetch_one(&mut conn)
+    .await?
+    .try_get("sum")?;
+
+    sqlx::query(
+        "INSERT INTO supply_cache (id, total, non_circulating, last_height, updated_at) \
+         VALUES (1, $1, $2, $3, now()) \
+         ON CONFLICT (id) DO UPDATE SET \
+         total = supply_cache.total + $1, \
+         non_circulating = $2, \
+         last_height = $3, \
+         updated_at = now()",
+    )
+    .bind(minted)
+    .bind(non_circulating)
+    .bind(max_height)
+    .execute(&mut conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Reads the cached `{total, circulating, non_circulating}` triple. `Ok(None)`
+/// means the cache genuinely has no row yet (`recompute_supply` hasn't run);
+/// any other query failure is propagated so callers don't mistake a transient
+/// DB error for "supply is zero".
+pub async fn supply(api: &Api) -> Result<Option<Supply>> {
+    let mut conn = api.storage.lock().await.acquire().await?;
+    let row = match sqlx::query("SELECT total, non_circulating FROM supply_cache WHERE id=1")
+        .fetch_one(&mut conn)
+        .await
+    {
+        Ok(row) => row,
+        Err(RowNotFound) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let total: i64 = row.try_get("total")?;
+    let non_circulating: i64 = row.try_get("non_circulating")?;
+    Ok(Some(Supply {
+        total,
+        circulating: total - non_circulating,
+        non_circulating,
+    }))
+}
+
+/// Sums only the native-asset records; a custom token's `IssueAsset` would
+/// otherwise inflate the FRA supply for an issuer that never minted FRA.
+fn extract_issued_amount(tx: &Value) -> i64 {
+    let mut minted = 0;
+    let operations = tx["body"]["operations"].as_array().cloned().unwrap_or_default();
+    for op in operations {
+        if let Some(issue) = op.get("IssueAsset") {
+            for record in issue["body"]["records"].as_array().cloned().unwrap_or_default() {
+                let output = &record[0];
+                if !is_native_asset(output) {
+                    continue;
+                }
+                if let Some(amount) = output["amount"]
+                    .get("NonConfidential")
+                    .and_then(|v| v.as_str())
+                    .and_then(|s| s.parse::<i64>().ok())
+                {
+                    minted += amount;
+                }
+            }
+        }
+    }
+    minted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issue_tx(amount: i64, asset_type: &str) -> Value {
+        serde_json::json!({
+            "body": {
+                "operations": [
+                    {"IssueAsset": {"body": {"records": [
+                        [{
+                            "public_key": "alice",
+                            "amount": {"NonConfidential": amount.to_string()},
+                            "asset_type": {"NonConfidential": asset_type},
+                        }, serde_json::Value::Null]
+                    ]}}}
+                ]
+            }
+        })
+    }
+
+    #[test]
+    fn sums_native_asset_issuance() {
+        let tx = issue_tx(1_000, FRA_ASSET_TYPE_CODE);
+        assert_eq!(extract_issued_amount(&tx), 1_000);
+    }
+
+    #[test]
+    fn ignores_custom_token_issuance() {
+        let tx = issue_tx(1_000, "some-other-token-code");
+        assert_eq!(extract_issued_amount(&tx), 0);
+    }
+}
@@ -0,0 +1,590 @@
+use crate::metrics;
+use crate::service::chain;
+use crate::subscription::Topic;
+use crate::supply;
+use crate::Api;
+use anyhow::Result;
+use module::schema::DelegationInfo;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::types::chrono::{Duration, Local};
+use sqlx::Error::RowNotFound;
+use sqlx::Row;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Jobs whose heartbeat is older than this are assumed dead and requeued.
+const HEARTBEAT_TIMEOUT_SECS: i64 = 60;
+
+pub const RECOMPUTE_STATISTICS_QUEUE: &str = "recompute_statistics";
+pub const FOLD_BALANCES_QUEUE: &str = "fold_balances";
+pub const RECOMPUTE_SUPPLY_QUEUE: &str = "recompute_supply";
+pub const RECOMPUTE_STAKING_HISTORY_QUEUE: &str = "recompute_staking_history";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Job {
+    pub id: Uuid,
+    pub queue: String,
+    pub job: Value,
+}
+
+/// Claims one `new` job off `queue`, flipping it to `running` and stamping a
+/// fresh heartbeat. Uses `FOR UPDATE SKIP LOCKED` so concurrent workers never
+/// double-claim the same row.
+pub async fn claim_job(api: &Api, queue: &str) -> Result<Option<Job>> {
+    let mut conn = api.storage.lock().await.acquire().await?;
+
+    let sql_str = String::from(
+        "UPDATE job_queue SET status='running', heartbeat=now() \
+         WHERE id = (SELECT id FROM job_queue WHERE queue=$1 AND status='new' \
+         ORDER BY heartbeat ASC FOR UPDATE SKIP LOCKED LIMIT 1) \
+         RETURNING id, queue, job",
+    );
+    let row_res = sqlx::query(sql_str.as_str())
+        .bind(queue)
+        .fetch_one(&mut conn)
+        .await;
+
+    let row = match row_res {
+        Ok(row) => row,
+        Err(RowNotFound) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(Some(Job {
+        id: row.try_get("id")?,
+        queue: row.try_get("queue")?,
+        job: row.try_get("job")?,
+    }))
+}
+
+pub async fn heartbeat(api: &Api, id: Uuid) -> Result<()> {
+    let mut conn = api.storage.lock().await.acquire().await?;
+    sqlx::query("UPDATE job_queue SET heartbeat=now() WHERE id=$1")
+        .bind(id)
+        .execute(&mut conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn complete_job(api: &Api, id: Uuid) -> Result<()> {
+    let mut conn = api.storage.lock().await.acquire().await?;
+    sqlx::query("DELETE FROM job_queue WHERE id=$1")
+        .bind(id)
+        .execute(&mut conn)
+        .await?;
+    Ok(())
+}
+
+/// Requeues jobs stuck in `running` whose heartbeat went stale, e.g. because
+/// the worker that claimed them crashed mid-job.
+pub async fn reap_stale_jobs(api: &Api) -> Result<()> {
+    let mut conn = api.storage.lock().await.acquire().await?;
+    let cutoff = Local::now().naive_local() - Duration::seconds(HEARTBEAT_TIMEOUT_SECS);
+    sqlx::query("UPDATE job_queue SET status='new' WHERE status='running' AND heartbeat<$1")
+        .bind(cutoff)
+        .execute(&mut conn)
+        .await?;
+    Ok(())
+}
+
+/// Enqueues a recurring `recompute_statistics` job if one isn't already
+/// queued or running.
+pub async fn schedule_recompute_statistics(api: &Api) -> Result<()> {
+    let mut conn = api.storage.lock().await.acquire().await?;
+    sqlx::query(
+        "INSERT INTO job_queue (queue, job, status, heartbeat) \
+         SELECT $1, '{}'::jsonb, 'new', now() \
+         WHERE NOT EXISTS (SELECT 1 FROM job_queue WHERE queue=$1)",
+    )
+    .bind(RECOMPUTE_STATISTICS_QUEUE)
+    .execute(&mut conn)
+    .await?;
+    Ok(())
+}
+
+/// Folds every `transaction` row indexed since `statistics_cache.last_height`
+/// into the rolling cache, so `statistics()` never has to scan the whole
+/// table again. Distinct addresses are tracked in `statistics_address` so the
+/// active-address count stays accurate across incremental runs.
+pub async fn recompute_statistics(api: &Api) -> Result<()> {
+    let mut conn = api.storage.lock().await.acquire().await?;
+
+    let last_height: i64 = match sqlx::query("SELECT last_height FROM statistics_cache WHERE id=1")
+        .fetch_one(&mut conn)
+        .await
+    {
+        Ok(row) => row.try_get("last_height")?,
+        Err(RowNotFound) => 0,
+        Err(err) => return Err(err.into()),
+    };
+
+    let new_txs: i64 = sqlx::query("SELECT COUNT(*) as cnt FROM transaction WHERE height>$1")
+        .bind(last_height)
+        .fetch_one(&mut conn)
+        .await?
+        .try_get("cnt")?;
+
+    let max_height: i64 = sqlx::query("SELECT COALESCE(MAX(height), $1) as h FROM transaction WHERE height>$1")
+        .bind(last_height)
+        .fetch_one(&mut conn)
+        .await?
+        .try_get("h")?;
+
+    let sql_str = String::from(
+        "SELECT jsonb_path_query(value,'$.body.operations[*].TransferAsset.body.transfer.outputs[*].public_key') as addr \
+         FROM transaction WHERE height>$1",
+    );
+    let rows = sqlx::query(sql_str.as_str())
+        .bind(last_height)
+        .fetch_all(&mut conn)
+        .await?;
+    let mut new_addresses: HashSet<String> = HashSet::new();
+    for row in rows {
+        let value: Value = row.try_get("addr")?;
+        if let Ok(addr) = serde_json::from_value::<String>(value) {
+            new_addresses.insert(addr);
+        }
+    }
+    for addr in &new_addresses {
+        sqlx::query("INSERT INTO statistics_address (address) VALUES ($1) ON CONFLICT DO NOTHING")
+            .bind(addr)
+            .execute(&mut conn)
+            .await?;
+    }
+
+    let t = Local::now().timestamp() - 3600 * 24;
+    let daily_txs: i64 = sqlx::query("SELECT COUNT(*) as cnt FROM transaction WHERE timestamp>=$1")
+        .bind(t)
+        .fetch_one(&mut conn)
+        .await?
+        .try_get("cnt")?;
+
+    let active_addresses: i64 = sqlx::query("SELECT COUNT(*) as cnt FROM statistics_address")
+        .fetch_one(&mut conn)
+        .await?
+        .try_get("cnt")?;
+
+    sqlx::query(
+        "INSERT INTO statistics_cache (id, total_txs, daily_txs, active_addresses, last_height, updated_at) \
+         VALUES (1, $1, $2, $3, $4, now()) \
+         ON CONFLICT (id) DO UPDATE SET \
+         total_txs = statistics_cache.total_txs + $1, \
+         daily_txs = $2, \
+         active_addresses = $3, \
+         last_height = $4, \
+         updated_at = now()",
+    )
+    .bind(new_txs)
+    .bind(daily_txs)
+    .bind(active_addresses)
+    .bind(max_height)
+    .execute(&mut conn)
+    .await?;
+
+    let total_txs: i64 = sqlx::query("SELECT total_txs FROM statistics_cache WHERE id=1")
+        .fetch_one(&mut conn)
+        .await?
+        .try_get("total_txs")?;
+
+    // `transaction` is written by the indexer as it catches up to the chain,
+    // so its own current max height is the best tip estimate this job has
+    // without a live node client; `max_height` is how far this job itself has
+    // folded, so the gap is indexing lag, not scanner lag.
+    let chain_tip_height: i64 = sqlx::query("SELECT COALESCE(MAX(height), 0) as h FROM transaction")
+        .fetch_one(&mut conn)
+        .await?
+        .try_get("h")?;
+    metrics::set_indexer_lag(max_height, chain_tip_height);
+    drop(conn);
+
+    api.subscriptions
+        .notify(
+            Topic::ChainStatistics,
+            serde_json::json!({
+                "code": 200,
+                "message": "",
+                "data": {
+                    "active_addresses": active_addresses,
+                    "total_txs": total_txs,
+                    "daily_txs": daily_txs,
+                },
+            }),
+        )
+        .await;
+
+    Ok(())
+}
+
+/// Folds every transaction indexed since `address_balance_cursor.last_height`
+/// into per-address balance deltas, covering `TransferAsset` inputs/outputs,
+/// `IssueAsset` mint records and `BarToAbar`/`AbarToBar` conversions, and
+/// applies them to `address_balance`. Only native-FRA amounts are folded in —
+/// see [`extract_balance_deltas`]. This backs `GET /addresses/largest` with
+/// an indexed aggregate instead of decoding every transaction per request.
+pub async fn fold_balances(api: &Api) -> Result<()> {
+    let mut conn = api.storage.lock().await.acquire().await?;
+
+    let last_height: i64 = match sqlx::query("SELECT last_height FROM address_balance_cursor WHERE id=1")
+        .fetch_one(&mut conn)
+        .await
+    {
+        Ok(row) => row.try_get("last_height")?,
+        Err(RowNotFound) => 0,
+        Err(err) => return Err(err.into()),
+    };
+
+    let sql_str =
+        String::from("SELECT height, value FROM transaction WHERE height>$1 ORDER BY height ASC");
+    let rows = sqlx::query(sql_str.as_str())
+        .bind(last_height)
+        .fetch_all(&mut conn)
+        .await?;
+
+    let mut deltas: std::collections::HashMap<String, i64> = std::collections::HashMap::new();
+    let mut max_height = last_height;
+    for row in &rows {
+        let height: i64 = row.try_get("height")?;
+        if height > max_height {
+            max_height = height;
+        }
+        let value: Value = row.try_get("value")?;
+        for (addr, amount) in extract_balance_deltas(&value) {
+            *deltas.entry(addr).or_insert(0) += amount;
+        }
+    }
+
+    for (addr, delta) in deltas {
+        sqlx::query(
+            "INSERT INTO address_balance (address, balance) VALUES ($1, $2) \
+             ON CONFLICT (address) DO UPDATE SET balance = address_balance.balance + $2",
+        )
+        .bind(&addr)
+        .bind(delta)
+        .execute(&mut conn)
+        .await?;
+    }
+
+    sqlx::query(
+        "INSERT INTO address_balance_cursor (id, last_height) VALUES (1, $1) \
+         ON CONFLICT (id) DO UPDATE SET last_height = $1",
+    )
+    .bind(max_height)
+    .execute(&mut conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Walks every operation in a transaction body and returns the `(address,
+/// amount)` deltas it implies. Credits come from `TransferAsset`/`IssueAsset`
+/// outputs and `AbarToBar` outputs (anon pool exits back to a transparent
+/// address); debits come from `TransferAsset` inputs and `BarToAbar` spends
+/// (transparent balance entering the anon pool), so a transfer nets to zero
+/// across sender and receiver and an abar round-trip nets to zero too.
+/// Records for any asset
+/// other than native FRA are skipped entirely: `address_balance` tracks FRA
+/// balances only, and folding a custom token's amount in would silently
+/// misstate it for an address that never touched FRA.
+fn extract_balance_deltas(tx: &Value) -> Vec<(String, i64)> {
+    let mut out = vec![];
+    let operations = tx["body"]["operations"].as_array().cloned().unwrap_or_default();
+    for op in operations {
+        if let Some(transfer) = op.get("TransferAsset") {
+            let body = &transfer["body"]["transfer"];
+            for output in body["outputs"].as_array().cloned().unwrap_or_default() {
+                if !supply::is_native_asset(&output) {
+                    continue;
+                }
+                if let (Some(addr), Some(amount)) =
+                    (output["public_key"].as_str(), extract_amount(&output["amount"]))
+                {
+                    out.push((addr.to_string(), amount));
+                }
+            }
+            for input in body["inputs"].as_array().cloned().unwrap_or_default() {
+                if !supply::is_native_asset(&input) {
+                    continue;
+                }
+                if let (Some(addr), Some(amount)) =
+                    (input["public_key"].as_str(), extract_amount(&input["amount"]))
+                {
+                    out.push((addr.to_string(), -amount));
+                }
+            }
+        } else if let Some(issue) = op.get("IssueAsset") {
+            for record in issue["body"]["records"].as_array().cloned().unwrap_or_default() {
+                let output = &record[0];
+                if !supply::is_native_asset(output) {
+                    continue;
+                }
+                if let (Some(addr), Some(amount)) =
+                    (output["public_key"].as_str(), extract_amount(&output["amount"]))
+                {
+                    out.push((addr.to_string(), amount));
+                }
+            }
+        } else if let Some(bar_to_abar) = op.get("BarToAbar") {
+            let input = &bar_to_abar["body"]["input"];
+            if supply::is_native_asset(input) {
+                if let (Some(addr), Some(amount)) =
+                    (input["public_key"].as_str(), extract_amount(&input["amount"]))
+                {
+                    out.push((addr.to_string(), -amount));
+                }
+            }
+        } else if let Some(abar_to_bar) = op.get("AbarToBar") {
+            let output = &abar_to_bar["body"]["output"];
+            if supply::is_native_asset(output) {
+                if let (Some(addr), Some(amount)) =
+                    (output["public_key"].as_str(), extract_amount(&output["amount"]))
+                {
+                    out.push((addr.to_string(), amount));
+                }
+            }
+        }
+    }
+    out
+}
+
+fn extract_amount(value: &Value) -> Option<i64> {
+    value
+        .get("NonConfidential")
+        .and_then(|v| v.as_str())
+        .and_then(|s| s.parse::<i64>().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::supply::FRA_ASSET_TYPE_CODE;
+
+    fn record(public_key: &str, amount: i64, asset_type: &str) -> Value {
+        serde_json::json!({
+            "public_key": public_key,
+            "amount": {"NonConfidential": amount.to_string()},
+            "asset_type": {"NonConfidential": asset_type},
+        })
+    }
+
+    #[test]
+    fn transfer_asset_credits_outputs_and_debits_inputs() {
+        let tx = serde_json::json!({
+            "body": {
+                "operations": [
+                    {"TransferAsset": {"body": {"transfer": {
+                        "inputs": [record("alice", 100, FRA_ASSET_TYPE_CODE)],
+                        "outputs": [record("bob", 100, FRA_ASSET_TYPE_CODE)],
+                    }}}}
+                ]
+            }
+        });
+        let deltas = extract_balance_deltas(&tx);
+        assert_eq!(deltas, vec![("bob".to_string(), 100), ("alice".to_string(), -100)]);
+    }
+
+    #[test]
+    fn issue_asset_credits_the_recipient() {
+        let tx = serde_json::json!({
+            "body": {
+                "operations": [
+                    {"IssueAsset": {"body": {"records": [
+                        [record("alice", 500, FRA_ASSET_TYPE_CODE), serde_json::Value::Null]
+                    ]}}}
+                ]
+            }
+        });
+        assert_eq!(extract_balance_deltas(&tx), vec![("alice".to_string(), 500)]);
+    }
+
+    #[test]
+    fn bar_to_abar_debits_and_abar_to_bar_credits() {
+        let bar_to_abar = serde_json::json!({
+            "body": {"operations": [
+                {"BarToAbar": {"body": {"input": record("alice", 200, FRA_ASSET_TYPE_CODE)}}}
+            ]}
+        });
+        assert_eq!(extract_balance_deltas(&bar_to_abar), vec![("alice".to_string(), -200)]);
+
+        let abar_to_bar = serde_json::json!({
+            "body": {"operations": [
+                {"AbarToBar": {"body": {"output": record("alice", 200, FRA_ASSET_TYPE_CODE)}}}
+            ]}
+        });
+        assert_eq!(extract_balance_deltas(&abar_to_bar), vec![("alice".to_string(), 200)]);
+    }
+
+    #[test]
+    fn non_native_asset_is_ignored() {
+        let tx = serde_json::json!({
+            "body": {
+                "operations": [
+                    {"TransferAsset": {"body": {"transfer": {
+                        "inputs": [],
+                        "outputs": [record("bob", 1_000_000, "some-other-token-code")],
+                    }}}}
+                ]
+            }
+        });
+        assert!(extract_balance_deltas(&tx).is_empty());
+    }
+}
+
+/// Runs a single claim/execute/complete cycle against `queue`. If the job
+/// fails, the error is returned and the job stays `running` until
+/// [`reap_stale_jobs`] requeues it once its heartbeat is older than
+/// `HEARTBEAT_TIMEOUT_SECS`, so a later poll retries it. Intended to be driven by a
+/// loop on a fixed interval alongside [`reap_stale_jobs`].
+pub async fn run_once(api: &Api, queue: &str) -> Result<()> {
+    reap_stale_jobs(api).await?;
+
+    let job = match claim_job(api, queue).await? {
+        Some(job) => job,
+        None => return Ok(()),
+    };
+
+    match job.queue.as_str() {
+        RECOMPUTE_STATISTICS_QUEUE => recompute_statistics(api).await?,
+        FOLD_BALANCES_QUEUE => fold_balances(api).await?,
+        RECOMPUTE_SUPPLY_QUEUE => supply::recompute_supply(api).await?,
+        RECOMPUTE_STAKING_HISTORY_QUEUE => recompute_staking_history(api).await?,
+        _ => {}
+    }
+
+    complete_job(api, job.id).await?;
+    schedule_recompute_statistics(api).await?;
+    schedule_fold_balances(api).await?;
+    schedule_recompute_supply(api).await?;
+    schedule_recompute_staking_history(api).await?;
+    Ok(())
+}
+
+/// Enqueues a recurring `recompute_staking_history` job if one isn't already
+/// queued or running.
+pub async fn schedule_recompute_staking_history(api: &Api) -> Result<()> {
+    let mut conn = api.storage.lock().await.acquire().await?;
+    sqlx::query(
+        "INSERT INTO job_queue (queue, job, status, heartbeat) \
+         SELECT $1, '{}'::jsonb, 'new', now() \
+         WHERE NOT EXISTS (SELECT 1 FROM job_queue WHERE queue=$1)",
+    )
+    .bind(RECOMPUTE_STAKING_HISTORY_QUEUE)
+    .execute(&mut conn)
+    .await?;
+    Ok(())
+}
+
+/// Walks `delegations` rows newer than the last processed height, decodes
+/// each `DelegationInfo` once via [`chain::decode_delegation`], and persists
+/// the scalar fields a range query actually needs into `staking_history` so
+/// `GET /staking/history` never repeats that decode per request.
+pub async fn recompute_staking_history(api: &Api) -> Result<()> {
+    let mut conn = api.storage.lock().await.acquire().await?;
+
+    let last_height: i64 = sqlx::query("SELECT COALESCE(MAX(height), 0) as h FROM staking_history")
+        .fetch_one(&mut conn)
+        .await?
+        .try_get("h")?;
+
+    let sql_str =
+        String::from("SELECT height, timestamp, info FROM delegations WHERE height>$1 ORDER BY height ASC");
+    let rows = sqlx::query(sql_str.as_str())
+        .bind(last_height)
+        .fetch_all(&mut conn)
+        .await?;
+
+    let current_supply = supply::supply(api).await?.unwrap_or_default();
+    let circulating_supply = if current_supply.circulating > 0 {
+        current_supply.circulating as f64
+    } else {
+        0.0
+    };
+
+    let mut latest: Option<(chain::DecodedDelegation, f64)> = None;
+    for row in rows {
+        let height: i64 = row.try_get("height")?;
+        let timestamp: i64 = row.try_get("timestamp")?;
+        let info_value: Value = row.try_get("info")?;
+        let delegation_info: DelegationInfo = match serde_json::from_value(info_value) {
+            Ok(info) => info,
+            Err(_) => continue,
+        };
+        let decoded = chain::decode_delegation(&delegation_info);
+        let stake_ratio = if circulating_supply > 0.0 {
+            decoded.total_stake as f64 / circulating_supply
+        } else {
+            0.0
+        };
+
+        sqlx::query(
+            "INSERT INTO staking_history \
+             (height, timestamp, apy, block_reward, stake_ratio, active_validator_count) \
+             VALUES ($1, $2, $3, $4, $5, $6) \
+             ON CONFLICT (height) DO NOTHING",
+        )
+        .bind(height)
+        .bind(timestamp)
+        .bind(decoded.apy)
+        .bind(decoded.block_reward as i64)
+        .bind(stake_ratio)
+        .bind(decoded.active_validators.len() as i64)
+        .execute(&mut conn)
+        .await?;
+
+        latest = Some((decoded, stake_ratio));
+    }
+    drop(conn);
+
+    if let Some((decoded, stake_ratio)) = latest {
+        api.subscriptions
+            .notify(
+                Topic::StakingInfo,
+                serde_json::json!({
+                    "code": 200,
+                    "message": "",
+                    "data": {
+                        "block_reward": decoded.block_reward,
+                        "stake_ratio": stake_ratio,
+                        "apy": decoded.apy,
+                        "active_validators": decoded.active_validators,
+                        "total_supply": current_supply.total,
+                        "circulating_supply": current_supply.circulating,
+                        "non_circulating_supply": current_supply.non_circulating,
+                    },
+                }),
+            )
+            .await;
+    }
+
+    Ok(())
+}
+
+/// Enqueues a recurring `recompute_supply` job if one isn't already queued or
+/// running.
+pub async fn schedule_recompute_supply(api: &Api) -> Result<()> {
+    let mut conn = api.storage.lock().await.acquire().await?;
+    sqlx::query(
+        "INSERT INTO job_queue (queue, job, status, heartbeat) \
+         SELECT $1, '{}'::jsonb, 'new', now() \
+         WHERE NOT EXISTS (SELECT 1 FROM job_queue WHERE queue=$1)",
+    )
+    .bind(RECOMPUTE_SUPPLY_QUEUE)
+    .execute(&mut conn)
+    .await?;
+    Ok(())
+}
+
+/// Enqueues a recurring `fold_balances` job if one isn't already queued or
+/// running.
+pub async fn schedule_fold_balances(api: &Api) -> Result<()> {
+    let mut conn = api.storage.lock().await.acquire().await?;
+    sqlx::query(
+        "INSERT INTO job_queue (queue, job, status, heartbeat) \
+         SELECT $1, '{}'::jsonb, 'new', now() \
+         WHERE NOT EXISTS (SELECT 1 FROM job_queue WHERE queue=$1)",
+    )
+    .bind(FOLD_BALANCES_QUEUE)
+    .execute(&mut conn)
+    .await?;
+    Ok(())
+}